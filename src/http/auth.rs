@@ -0,0 +1,120 @@
+//! HMAC request/response authentication
+//!
+//! Requests and responses are authenticated with an `HMAC-SHA256` digest keyed by a shared secret
+//! read from `Config::secret_file`. Every incoming request other than an `OPTIONS` preflight
+//! carries the digest of its body in the `x-racerd-hmac` header (base64 encoded); replies whose
+//! handler stashed a [`SignedBody`](struct.SignedBody.html) are signed under the same key and
+//! header so clients can verify the server in turn.
+
+use iron::prelude::*;
+use iron::{BeforeMiddleware, AfterMiddleware, status};
+use iron::method::Method;
+use iron::typemap::Key;
+
+use bodyparser;
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+
+use data_encoding::base64;
+
+/// Header carrying the base64-encoded request/response digest.
+const HMAC_HEADER: &'static str = "x-racerd-hmac";
+
+/// Serialized response body stashed in the `Response` typemap by each handler so the
+/// after-middleware signs exactly the bytes that are sent back to the client. When a handler does
+/// not set it, no signature is emitted — signing an absent body would hand the client an
+/// `x-racerd-hmac` header that could never match what it actually receives.
+pub struct SignedBody;
+
+impl Key for SignedBody {
+    type Value = String;
+}
+
+/// Compute the base64-encoded `HMAC-SHA256` of `bytes` under `secret`.
+fn sign(secret: &[u8], bytes: &[u8]) -> String {
+    let mut mac = Hmac::new(Sha256::new(), secret);
+    mac.input(bytes);
+    base64::encode(mac.result().code())
+}
+
+/// `401 Unauthorized` for a missing or mismatched signature.
+fn unauthorized() -> IronError {
+    use std::io::{Error, ErrorKind};
+    let err = Error::new(ErrorKind::PermissionDenied, "invalid or missing request signature");
+    IronError::new(err, status::Unauthorized)
+}
+
+/// Verifies the `x-racerd-hmac` header on every incoming request before it reaches a handler.
+pub struct Authenticator {
+    secret: Vec<u8>,
+}
+
+impl Authenticator {
+    pub fn new(secret: Vec<u8>) -> Authenticator {
+        Authenticator { secret: secret }
+    }
+}
+
+impl BeforeMiddleware for Authenticator {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        // CORS preflight probes never carry the `x-racerd-hmac` header; authenticating them would
+        // turn every preflight into a 401 and break cross-origin access entirely.
+        if req.method == Method::Options {
+            return Ok(());
+        }
+
+        // The header itself is never part of the signed bytes, so reading it here is safe.
+        let provided = match req.headers.get_raw(HMAC_HEADER) {
+            Some(raw) if raw.len() == 1 => raw[0].clone(),
+            _ => return Err(unauthorized()),
+        };
+
+        // Request bodies are the UTF-8 JSON payload the handlers parse. A body-less request (eg.
+        // the `ping` health check) signs the empty byte string; a body we cannot read back is a
+        // signature failure rather than a silent empty digest.
+        let body = match req.get::<bodyparser::Raw>() {
+            Ok(Some(body)) => body,
+            Ok(None) => String::new(),
+            Err(_) => return Err(unauthorized()),
+        };
+        let expected = sign(&self.secret, body.as_bytes());
+
+        // Compare over equal-length slices only — `fixed_time_eq` requires it, and a timing-safe
+        // comparison keeps us from leaking the digest one byte at a time.
+        if provided.len() == expected.len() && fixed_time_eq(&provided, expected.as_bytes()) {
+            Ok(())
+        } else {
+            Err(unauthorized())
+        }
+    }
+}
+
+/// Signs the outgoing response body with the shared secret.
+pub struct Signer {
+    secret: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: Vec<u8>) -> Signer {
+        Signer { secret: secret }
+    }
+}
+
+impl AfterMiddleware for Signer {
+    fn after(&self, _: &mut Request, mut res: Response) -> IronResult<Response> {
+        // Sign only what the handler actually serialized; never emit a digest over an empty body
+        // the client would then fail to verify.
+        let digest = res.extensions
+            .get::<SignedBody>()
+            .map(|body| sign(&self.secret, body.as_bytes()));
+
+        if let Some(digest) = digest {
+            res.headers.set_raw(HMAC_HEADER, vec![digest.into_bytes()]);
+        }
+
+        Ok(res)
+    }
+}
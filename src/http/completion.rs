@@ -0,0 +1,26 @@
+//! Code completion endpoint
+//!
+//! `POST /list_completions` — lists completion candidates at the requested cursor position.
+
+use iron::prelude::*;
+use iron::status;
+
+use bodyparser;
+
+use engine::{Context, SemanticEngine};
+
+use super::{json_response, timeout};
+
+/// List completion candidates for the symbol being typed at the cursor.
+pub fn list(req: &mut Request) -> IronResult<Response> {
+    let context = match req.get::<bodyparser::Struct<Context>>() {
+        Ok(Some(context)) => context,
+        Ok(None) => return Ok(Response::with(status::BadRequest)),
+        Err(err) => return Ok(Response::with((status::BadRequest, format!("{}", err)))),
+    };
+
+    match try!(timeout::dispatch(req, move |engine| engine.list_completions(&context))) {
+        Ok(completions) => json_response(&completions),
+        Err(err) => Ok(Response::with((status::InternalServerError, format!("{:?}", err)))),
+    }
+}
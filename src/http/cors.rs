@@ -0,0 +1,64 @@
+//! CORS support for browser-based editors
+//!
+//! An [`AfterMiddleware`](struct.Cors.html) echoes back the single request `Origin` when it matches
+//! the configured allowlist — never a blanket `*`, since requests carry HMAC credentials — along
+//! with the methods and headers the REST endpoints accept. The [`preflight`](fn.preflight.html)
+//! handler answers `OPTIONS` probes with `204` and an empty body; the same after-middleware then
+//! decorates that response with the CORS headers.
+
+use iron::prelude::*;
+use iron::{AfterMiddleware, status};
+
+/// Read the first `Origin` request header as a string, if present.
+fn request_origin(req: &Request) -> Option<String> {
+    req.headers
+        .get_raw("origin")
+        .and_then(|raw| raw.first())
+        .and_then(|bytes| String::from_utf8(bytes.clone()).ok())
+}
+
+/// Answers `OPTIONS` preflight probes with an empty `204`; [`Cors`](struct.Cors.html) attaches the
+/// actual CORS headers on the way out.
+pub fn preflight(_: &mut Request) -> IronResult<Response> {
+    Ok(Response::with(status::NoContent))
+}
+
+/// Echoes a single allowlisted `Origin` and the accompanying CORS headers onto every response.
+pub struct Cors {
+    allowed: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(allowed: Vec<String>) -> Cors {
+        Cors { allowed: allowed }
+    }
+
+    fn decorate(&self, req: &Request, res: &mut Response) {
+        // The response body depends on the request `Origin`, so caches must key on it.
+        res.headers.set_raw("Vary", vec![b"Origin".to_vec()]);
+
+        if let Some(origin) = request_origin(req) {
+            if self.allowed.iter().any(|allowed| allowed == &origin) {
+                res.headers.set_raw("Access-Control-Allow-Origin", vec![origin.into_bytes()]);
+                res.headers.set_raw("Access-Control-Allow-Methods",
+                                    vec![b"GET, POST, OPTIONS".to_vec()]);
+                res.headers.set_raw("Access-Control-Allow-Headers",
+                                    vec![b"Content-Type, x-racerd-hmac".to_vec()]);
+                res.headers.set_raw("Access-Control-Allow-Credentials", vec![b"true".to_vec()]);
+            }
+        }
+    }
+}
+
+impl AfterMiddleware for Cors {
+    fn after(&self, req: &mut Request, mut res: Response) -> IronResult<Response> {
+        self.decorate(req, &mut res);
+        Ok(res)
+    }
+
+    // Error responses (eg. a 401 or 408) carry the headers too, so browsers can read them.
+    fn catch(&self, req: &mut Request, mut err: IronError) -> IronResult<Response> {
+        self.decorate(req, &mut err.response);
+        Err(err)
+    }
+}
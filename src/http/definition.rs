@@ -0,0 +1,28 @@
+//! Definition lookup endpoint
+//!
+//! `POST /find_definition` — resolves the definition of the symbol under the cursor. See the
+//! module-level docs for the request/response shape.
+
+use iron::prelude::*;
+use iron::status;
+
+use bodyparser;
+
+use engine::{Context, SemanticEngine};
+
+use super::{json_response, timeout};
+
+/// Look up the definition of the symbol at the requested cursor position.
+pub fn find(req: &mut Request) -> IronResult<Response> {
+    let context = match req.get::<bodyparser::Struct<Context>>() {
+        Ok(Some(context)) => context,
+        Ok(None) => return Ok(Response::with(status::BadRequest)),
+        Err(err) => return Ok(Response::with((status::BadRequest, format!("{}", err)))),
+    };
+
+    match try!(timeout::dispatch(req, move |engine| engine.find_definition(&context))) {
+        Ok(Some(definition)) => json_response(&definition),
+        Ok(None) => Ok(Response::with(status::NotFound)),
+        Err(err) => Ok(Response::with((status::InternalServerError, format!("{:?}", err)))),
+    }
+}
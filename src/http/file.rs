@@ -0,0 +1,27 @@
+//! File parsing endpoint
+//!
+//! `POST /parse_file` — parses the supplied buffers, priming the engine and surfacing any
+//! compilation diagnostics.
+
+use iron::prelude::*;
+use iron::status;
+
+use bodyparser;
+
+use engine::{Context, SemanticEngine};
+
+use super::{json_response, timeout};
+
+/// Parse the buffers in the request, returning the engine's diagnostics.
+pub fn parse(req: &mut Request) -> IronResult<Response> {
+    let context = match req.get::<bodyparser::Struct<Context>>() {
+        Ok(Some(context)) => context,
+        Ok(None) => return Ok(Response::with(status::BadRequest)),
+        Err(err) => return Ok(Response::with((status::BadRequest, format!("{}", err)))),
+    };
+
+    match try!(timeout::dispatch(req, move |engine| engine.parse_file(&context))) {
+        Ok(parsed) => json_response(&parsed),
+        Err(err) => Ok(Response::with((status::InternalServerError, format!("{:?}", err)))),
+    }
+}
@@ -0,0 +1,82 @@
+//! StatsD metrics emission
+//!
+//! A [`BeforeMiddleware`](struct.RequestTimer.html) stamps a start instant into the request
+//! typemap and an [`AfterMiddleware`](struct.Metrics.html) computes the elapsed request time and
+//! flushes counters and timers over a buffered UDP sink. The timer is stamped at the front of the
+//! before-chain, so it covers total request latency (parsing, auth, and engine work), not engine
+//! time alone. Operators can then watch latency and error rates in production without parsing the
+//! log output.
+
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::Instant;
+
+use iron::prelude::*;
+use iron::{BeforeMiddleware, AfterMiddleware};
+use iron::status::Status;
+use iron::typemap::Key;
+
+use cadence::prelude::*;
+use cadence::{StatsdClient, BufferedUdpMetricSink};
+
+/// Start instant stamped into the request typemap by [`RequestTimer`](struct.RequestTimer.html).
+pub struct StartTime;
+
+impl Key for StartTime {
+    type Value = Instant;
+}
+
+/// Records when request processing began so the latency can be measured in the after-middleware.
+pub struct RequestTimer;
+
+impl BeforeMiddleware for RequestTimer {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        req.extensions.insert::<StartTime>(Instant::now());
+        Ok(())
+    }
+}
+
+/// Flushes a request counter, a status-keyed response counter, and a request-latency timer for
+/// every handled request.
+pub struct Metrics {
+    client: Arc<StatsdClient>,
+}
+
+impl Metrics {
+    /// Connect a buffered UDP StatsD sink at `host:port` under the `racerd` metric prefix.
+    pub fn new(host: &str, port: u16) -> io::Result<Metrics> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        let sink = try!(BufferedUdpMetricSink::from((host, port), socket));
+        Ok(Metrics { client: Arc::new(StatsdClient::from_sink("racerd", sink)) })
+    }
+
+    /// Emit the counters and timer for a finished request.
+    fn emit(&self, req: &Request, status: Option<Status>) {
+        let route = req.url.path().into_iter().next().unwrap_or("").to_owned();
+
+        let _ = self.client.incr(&format!("{}.requests", route));
+
+        let code = status.map(|s| s.to_u16()).unwrap_or(0);
+        let class = if code >= 400 { "error" } else { "success" };
+        let _ = self.client.incr(&format!("{}.responses.{}.{}", route, class, code));
+
+        if let Some(start) = req.extensions.get::<StartTime>() {
+            let elapsed = start.elapsed();
+            let millis = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+            let _ = self.client.time(&format!("{}.latency_ms", route), millis);
+        }
+    }
+}
+
+impl AfterMiddleware for Metrics {
+    fn after(&self, req: &mut Request, res: Response) -> IronResult<Response> {
+        self.emit(req, res.status);
+        Ok(res)
+    }
+
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        self.emit(req, Some(err.response.status.unwrap_or(Status::InternalServerError)));
+        Err(err)
+    }
+}
@@ -35,7 +35,7 @@
 //! # Planned features
 //! ✓ Definition lookup
 //!
-//! ☐ HMAC Auth for all endpoints
+//! ✓ HMAC Auth for all endpoints
 //!
 //! ☐ Code completions
 //!
@@ -49,6 +49,12 @@ pub mod definition;
 mod file;
 mod completion;
 mod ping;
+mod auth;
+mod timeout;
+mod metrics;
+mod cors;
+mod stream;
+mod panic;
 
 use ::engine::SemanticEngine;
 
@@ -59,6 +65,14 @@ use iron::typemap::Key;
 pub enum Error {
     /// Error occurred in underlying http server lib
     HttpServer(::hyper::Error),
+    /// Error occurred reading the HMAC secret file
+    SecretFile(::std::io::Error),
+    /// Error occurred connecting the StatsD metrics sink
+    Metrics(::std::io::Error),
+    /// Error occurred binding the streaming WebSocket listener
+    Stream(::std::io::Error),
+    /// Error occurred binding the panic error-reporting sink
+    ErrorSink(::std::io::Error),
     // Error occurred in http framework layer
     // HttpApp(::iron::IronError),
 }
@@ -69,15 +83,37 @@ impl From<::hyper::Error> for Error {
     }
 }
 
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Error {
+        Error::SecretFile(err)
+    }
+}
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 // -------------------------------------------------------------------------------------------------
 // This is the middleware which attaches a completion engine to a given request
+
+/// Shared, boxed engine handle reused across requests and the streaming connections.
+pub type SharedEngine = ::std::sync::Arc<::std::sync::Mutex<Box<SemanticEngine + Send>>>;
+
 #[derive(Debug, Clone)]
 pub struct EngineProvider;
 
 impl Key for EngineProvider {
-    type Value = Box<SemanticEngine + Send>;
+    type Value = SharedEngine;
+}
+
+/// Inserts a clone of the shared engine handle into each request's typemap. Sharing one handle
+/// (rather than `persistent::Write`'s private `Arc`) lets the streaming listener reuse the same
+/// engine as the REST handlers.
+struct ProvideEngine(SharedEngine);
+
+impl ::iron::BeforeMiddleware for ProvideEngine {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        req.extensions.insert::<EngineProvider>(self.0.clone());
+        Ok(())
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -101,14 +137,34 @@ impl Key for EngineProvider {
 /// ```
 ///
 pub fn serve<E: SemanticEngine + Send + 'static>(config: &Config, engine: E) -> Result<Server> {
-    use persistent::{Read, Write};
+    use std::time::Duration;
+    use persistent::Read;
     use logger::Logger;
 
     let mut chain = Chain::new(router!(
-        post "/parse_file"       => file::parse,
-        post "/find_definition"  => definition::find,
-        post "/list_completions" => completion::list,
-        get  "/ping"             => ping::pong));
+        post    "/parse_file"       => file::parse,
+        post    "/find_definition"  => definition::find,
+        post    "/list_completions" => completion::list,
+        get     "/ping"             => ping::pong,
+        options "/parse_file"       => cors::preflight,
+        options "/find_definition"  => cors::preflight,
+        options "/list_completions" => cors::preflight));
+
+    // Isolate handler panics as structured 500s so a racer crash can't drop the connection. This
+    // wraps the router, so the 500 it produces still flows through the metrics/log after-chain.
+    // When an error sink is configured, captured panics are also forwarded to it as JSON datagrams.
+    match config.error_sink {
+        Some(ref addr) => {
+            let socket = try!(::std::net::UdpSocket::bind("0.0.0.0:0").map_err(Error::ErrorSink));
+            let addr = addr.clone();
+            chain.link_around(panic::PanicCatcher::with_sink(move |report: &panic::PanicReport| {
+                if let Ok(payload) = ::serde_json::to_string(report) {
+                    let _ = socket.send_to(payload.as_bytes(), &addr[..]);
+                }
+            }));
+        }
+        None => chain.link_around(panic::PanicCatcher::new()),
+    }
 
     // Logging middleware
     let (log_before, log_after) = Logger::new(None);
@@ -118,16 +174,76 @@ pub fn serve<E: SemanticEngine + Send + 'static>(config: &Config, engine: E) ->
         chain.link_before(log_before);
     }
 
-    chain.link_before(Write::<EngineProvider>::one(Box::new(engine)));
+    // Stamp a start instant as early as possible so the timer covers engine time.
+    if config.statsd_host.is_some() {
+        chain.link_before(metrics::RequestTimer);
+    }
+
+    let engine: SharedEngine =
+        ::std::sync::Arc::new(::std::sync::Mutex::new(Box::new(engine) as Box<SemanticEngine + Send>));
+    chain.link_before(ProvideEngine(engine.clone()));
 
     // Body parser middlerware
     chain.link_before(Read::<::bodyparser::MaxBodyLength>::one(1024 * 1024 * 10));
 
+    // Share the per-request processing deadline with the handlers so engine calls can be run on a
+    // worker thread and abandoned once they blow past it.
+    if config.request_timeout_ms > 0 {
+        let deadline = Duration::from_millis(config.request_timeout_ms);
+        chain.link_before(Read::<timeout::Timeout>::one(deadline));
+    }
+
+    // HMAC authentication — every endpoint (including `ping`) must present a valid signature;
+    // only the `OPTIONS` preflight routes are exempt.
+    // Fail closed: if a secret file is configured but unreadable, refuse to start.
+    if let Some(ref path) = config.secret_file {
+        let secret = try!(read_secret(path));
+        chain.link_before(auth::Authenticator::new(secret.clone()));
+        chain.link_after(auth::Signer::new(secret));
+    }
+
+    // Flush metrics once the final status is known.
+    if let Some(ref host) = config.statsd_host {
+        let sink = try!(metrics::Metrics::new(host, config.statsd_port).map_err(Error::Metrics));
+        chain.link_after(sink);
+    }
+
+    // Echo allowlisted origins onto responses (including the OPTIONS preflight replies).
+    if !config.cors_allowed_origins.is_empty() {
+        chain.link_after(cors::Cors::new(config.cors_allowed_origins.clone()));
+    }
+
     // log_after must be last middleware in after chain
     if config.print_http_logs {
         chain.link_after(log_after);
     }
 
+    // Streaming WebSocket listener. Iron does not expose the underlying socket for an in-place
+    // upgrade, so streaming connections are accepted on their own port while the REST routes keep
+    // serving on the Iron listener; both share the one engine handle.
+    if config.stream_port != 0 {
+        let deadline = if config.request_timeout_ms > 0 {
+            Some(Duration::from_millis(config.request_timeout_ms))
+        } else {
+            None
+        };
+
+        let ws_host = format!("0.0.0.0:{}", config.stream_port);
+        let server = try!(::websocket::sync::Server::bind(&ws_host[..]).map_err(Error::Stream));
+        let ws_engine = engine.clone();
+
+        ::std::thread::spawn(move || {
+            for connection in server.filter_map(::std::result::Result::ok) {
+                let engine = ws_engine.clone();
+                ::std::thread::spawn(move || {
+                    if let Ok(client) = connection.accept() {
+                        stream::serve(engine, deadline, client);
+                    }
+                });
+            }
+        });
+    }
+
     let app = Iron::new(chain);
     let host = format!("0.0.0.0:{}", config.port);
 
@@ -136,6 +252,33 @@ pub fn serve<E: SemanticEngine + Send + 'static>(config: &Config, engine: E) ->
     })
 }
 
+/// Build a `200 OK` JSON response from `value`, stashing the serialized body so the HMAC
+/// after-middleware signs exactly the bytes the client receives.
+fn json_response<T: ::serde::Serialize>(value: &T) -> IronResult<Response> {
+    use iron::headers::ContentType;
+    use iron::status;
+
+    let body = match ::serde_json::to_string(value) {
+        Ok(body) => body,
+        Err(err) => return Ok(Response::with((status::InternalServerError, format!("{}", err)))),
+    };
+
+    let mut res = Response::with((status::Ok, body.clone()));
+    res.headers.set(ContentType::json());
+    res.extensions.insert::<auth::SignedBody>(body);
+    Ok(res)
+}
+
+/// Read the shared HMAC secret from `path`, failing closed if it cannot be read.
+fn read_secret<P: AsRef<::std::path::Path>>(path: P) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut file = try!(::std::fs::File::open(path));
+    let mut secret = Vec::new();
+    try!(file.read_to_end(&mut secret));
+    Ok(secret)
+}
+
 /// Wrapper type with information and control of the underlying HTTP server
 ///
 /// This type can only be created via the [`serve`](fn.serve.html) function.
@@ -0,0 +1,121 @@
+//! Panic isolation for handler invocations
+//!
+//! Racer can panic on malformed input; left unchecked, a panic in `definition::find` or
+//! `completion::list` tears down the Iron worker and the client sees only a dropped connection.
+//! This [`AroundMiddleware`](struct.PanicCatcher.html) wraps each handler in `catch_unwind`,
+//! captures the panic payload and originating route, and returns a structured JSON `500` carrying
+//! an error id so the connection closes cleanly. Because the `500` is returned as an `Ok` response,
+//! the metrics and log middleware still observe it as a failed request. An optional sink forwards
+//! the captured context to an external error-reporting service.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use iron::prelude::*;
+use iron::{AroundMiddleware, Handler, status};
+use iron::headers::ContentType;
+use iron::modifiers::Header;
+
+use serde_json;
+
+/// Monotonic source of error ids, so operators can correlate a client-facing id with a log line.
+static PANIC_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Context captured from a handler panic.
+#[derive(Serialize)]
+pub struct PanicReport {
+    /// Opaque id returned to the client and handed to the sink.
+    pub error_id: String,
+    /// Route whose handler panicked (eg. `find_definition`).
+    pub route: String,
+    /// Best-effort rendering of the panic payload.
+    pub message: String,
+}
+
+/// JSON body shape sent back to the client on a captured panic.
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error_id: &'a str,
+    error: &'a str,
+}
+
+impl PanicReport {
+    fn into_response(self) -> Response {
+        let body = ErrorBody { error_id: &self.error_id, error: "internal server error" };
+        let json = serde_json::to_string(&body).unwrap_or_else(|_| "{}".to_owned());
+        Response::with((status::InternalServerError, Header(ContentType::json()), json))
+    }
+}
+
+/// Pull a human-readable message out of an arbitrary panic payload.
+fn payload_message(payload: &Box<::std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+type Sink = Arc<Box<Fn(&PanicReport) + Send + Sync>>;
+
+/// Wraps each handler invocation in `catch_unwind`.
+pub struct PanicCatcher {
+    sink: Option<Sink>,
+}
+
+impl PanicCatcher {
+    /// Catch panics and surface them as structured `500`s without any external reporting.
+    pub fn new() -> PanicCatcher {
+        PanicCatcher { sink: None }
+    }
+
+    /// As [`new`](#method.new), but also forward each captured panic to `sink`.
+    pub fn with_sink<F>(sink: F) -> PanicCatcher
+        where F: Fn(&PanicReport) + Send + Sync + 'static
+    {
+        PanicCatcher { sink: Some(Arc::new(Box::new(sink))) }
+    }
+}
+
+impl AroundMiddleware for PanicCatcher {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        Box::new(CatchingHandler { handler: handler, sink: self.sink })
+    }
+}
+
+struct CatchingHandler {
+    handler: Box<Handler>,
+    sink: Option<Sink>,
+}
+
+impl Handler for CatchingHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let route = req.url.path().join("/");
+
+        match panic::catch_unwind(AssertUnwindSafe(|| self.handler.handle(req))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let report = PanicReport {
+                    error_id: format!("panic-{}", PANIC_COUNTER.fetch_add(1, Ordering::SeqCst)),
+                    route: route,
+                    message: payload_message(&payload),
+                };
+
+                // Always record the panic server-side so the client-facing error id can be
+                // correlated with a log line; the optional sink is an additional forward, not a
+                // replacement for logging.
+                error!("handler panic [{}] on route `{}`: {}",
+                       report.error_id, report.route, report.message);
+
+                if let Some(ref sink) = self.sink {
+                    sink(&report);
+                }
+
+                Ok(report.into_response())
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Health check endpoint
+//!
+//! `GET /ping` — a liveness probe that, with HMAC auth enabled, also validates the shared secret.
+
+use iron::prelude::*;
+
+use super::json_response;
+
+/// Response body for a successful health check.
+#[derive(Serialize)]
+struct Pong {
+    message: &'static str,
+}
+
+/// Answer a health check.
+pub fn pong(_: &mut Request) -> IronResult<Response> {
+    json_response(&Pong { message: "pong" })
+}
@@ -0,0 +1,189 @@
+//! WebSocket endpoint for streaming queries over a persistent connection
+//!
+//! Editors that fire a completion query on every keystroke pay a reconnect/handshake cost per
+//! request against the REST routes. A single long-lived WebSocket connection instead multiplexes
+//! `find_definition`, `list_completions`, and `parse_file` as framed JSON messages. Each client
+//! message carries a client-chosen `id` that is echoed on every reply so responses can be
+//! correlated even when they arrive out of order, and `list_completions` pushes one frame per
+//! candidate instead of a single response. The candidates are framed once the `SemanticEngine` has
+//! returned its complete list; the engine's own search is not streamed. A terminal frame with
+//! `done: true` closes each request.
+//!
+//! Iron does not expose the underlying socket for a protocol upgrade, so the loop is written
+//! against an already-upgraded `websocket` client: [`serve`](fn.serve.html) is driven from a
+//! WebSocket listener sharing the boxed [`EngineProvider`](../struct.EngineProvider.html) engine,
+//! while the existing HTTP routes keep running unchanged on the Iron listener.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{self, Value};
+use websocket::OwnedMessage;
+use websocket::message::Message;
+use websocket::sync::{Client, Writer};
+use websocket::stream::sync::Stream;
+
+use engine::SemanticEngine;
+
+use super::{timeout, SharedEngine};
+
+/// A single framed request pulled off the socket.
+#[derive(Debug, Deserialize)]
+struct Command {
+    /// Client-chosen id, echoed verbatim on every matching reply frame.
+    id: String,
+    /// One of `find_definition`, `list_completions`, or `parse_file`.
+    op: String,
+    /// Query payload, matching the body accepted by the corresponding REST route.
+    #[serde(default)]
+    params: Value,
+}
+
+/// A framed reply pushed back for a given request id. Streamed operations emit several `result`
+/// frames followed by a single `done` frame; errors emit one `done` frame carrying `error`.
+#[derive(Debug, Serialize)]
+struct Frame {
+    id: String,
+    done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A per-request handle for pushing frames back over the socket from a worker thread. The writer is
+/// shared behind a mutex so several in-flight requests can interleave their frames safely.
+struct Responder<W: Write> {
+    id: String,
+    writer: Arc<Mutex<Writer<W>>>,
+}
+
+impl<W: Write> Responder<W> {
+    fn send(&self, frame: Frame) -> bool {
+        let text = serde_json::to_string(&frame).unwrap_or_default();
+        self.writer.lock().unwrap().send_message(&Message::text(text)).is_ok()
+    }
+
+    /// Emit a `result` frame; `done` closes the request.
+    fn emit(&self, result: Option<Value>, done: bool) -> bool {
+        self.send(Frame { id: self.id.clone(), done: done, result: result, error: None })
+    }
+
+    /// Emit a terminal `error` frame.
+    fn fail(&self, error: String) {
+        let _ = self.send(Frame { id: self.id.clone(), done: true, result: None, error: Some(error) });
+    }
+}
+
+/// Deserialize `params` into the engine context shared by the REST routes.
+fn context(params: &Value) -> Result<::engine::Context, String> {
+    serde_json::from_value(params.clone()).map_err(|e| e.to_string())
+}
+
+/// Run one engine call, bounding it by `deadline` when configured. The engine is locked on the
+/// worker thread so a query that blows past its deadline releases this request without blocking the
+/// socket (the detached racer search still runs to completion — see `timeout`).
+fn call<T, F>(engine: SharedEngine, deadline: Option<Duration>, work: F) -> Result<T, String>
+    where F: FnOnce(&SemanticEngine) -> ::engine::Result<T> + Send + 'static,
+          T: Send + 'static
+{
+    let run = move || {
+        let engine = engine.lock().unwrap();
+        work(&**engine)
+    };
+
+    match deadline {
+        Some(deadline) => match timeout::run(deadline, run) {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err("request processing exceeded the configured timeout".to_owned()),
+        },
+        None => run().map_err(|e| e.to_string()),
+    }
+}
+
+/// Serialize one engine result into a JSON value for a frame.
+fn value<T: ::serde::Serialize>(item: &T) -> Result<Value, String> {
+    serde_json::to_value(item).map_err(|e| e.to_string())
+}
+
+/// Run a single command against the engine, streaming frames through `responder`.
+fn dispatch<W: Write>(engine: SharedEngine,
+                      deadline: Option<Duration>,
+                      command: Command,
+                      responder: &Responder<W>) {
+    let result = match &command.op[..] {
+        "find_definition" => context(&command.params)
+            .and_then(|ctx| call(engine, deadline, move |e| e.find_definition(&ctx)))
+            .and_then(|def| value(&def).map(|v| responder.emit(Some(v), true))),
+
+        "parse_file" => context(&command.params)
+            .and_then(|ctx| call(engine, deadline, move |e| e.parse_file(&ctx)))
+            .and_then(|parsed| value(&parsed).map(|v| responder.emit(Some(v), true))),
+
+        // Push each completion as its own frame so the client can render candidates incrementally,
+        // then close the request with a `done` frame.
+        "list_completions" => context(&command.params)
+            .and_then(|ctx| call(engine, deadline, move |e| e.list_completions(&ctx)))
+            .map(|completions| {
+                for completion in &completions {
+                    match value(completion) {
+                        Ok(v) => if !responder.emit(Some(v), false) { return true; },
+                        Err(e) => { responder.fail(e); return true; }
+                    }
+                }
+                responder.emit(None, true)
+            }),
+
+        other => Err(format!("unknown op: {}", other)),
+    };
+
+    if let Err(error) = result {
+        responder.fail(error);
+    }
+}
+
+/// Serve framed queries over an accepted WebSocket client until the peer hangs up.
+///
+/// Each command is handled on its own worker thread so a slow query does not stall reads on the
+/// socket; access to the single boxed engine is serialized by its mutex, and each engine call is
+/// bounded by `deadline` when one is configured.
+pub fn serve<S>(engine: SharedEngine, deadline: Option<Duration>, client: Client<S>)
+    where S: Stream + Send + 'static
+{
+    let (mut receiver, sender) = match client.split() {
+        Ok(halves) => halves,
+        Err(_) => return,
+    };
+    let sender = Arc::new(Mutex::new(sender));
+
+    for message in receiver.incoming_messages() {
+        let message = match message {
+            Ok(message) => message,
+            Err(_) => break,
+        };
+
+        let text = match message {
+            OwnedMessage::Text(text) => text,
+            OwnedMessage::Ping(payload) => {
+                let _ = sender.lock().unwrap().send_message(&Message::pong(payload));
+                continue;
+            }
+            OwnedMessage::Close(_) => break,
+            _ => continue,
+        };
+
+        let command = match serde_json::from_str::<Command>(&text) {
+            Ok(command) => command,
+            Err(_) => continue,
+        };
+
+        let engine = engine.clone();
+        let responder = Responder { id: command.id.clone(), writer: sender.clone() };
+
+        thread::spawn(move || {
+            dispatch(engine, deadline, command, &responder);
+        });
+    }
+}
@@ -0,0 +1,88 @@
+//! Per-request processing timeout
+//!
+//! A pathological buffer can send racer into a very long search and pin an Iron worker
+//! indefinitely. Callers run the `SemanticEngine` call through [`run`](fn.run.html) on a dedicated
+//! worker thread; if it does not finish within the configured deadline the caller stops waiting and
+//! the client is told the request timed out (`408 Request Timeout` on the REST path via
+//! [`timed_out`](fn.timed_out.html)). Note that the detached worker thread keeps running racer to
+//! completion in the background — racer work is not interruptible — so this frees the serving
+//! thread but does not bound the CPU the abandoned computation ultimately burns.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use iron::prelude::*;
+use iron::status;
+use iron::typemap::Key;
+
+use engine::SemanticEngine;
+
+use super::EngineProvider;
+
+/// Shared per-request processing deadline, injected into the request typemap by `http::serve`.
+pub struct Timeout;
+
+impl Key for Timeout {
+    type Value = Duration;
+}
+
+/// Read the per-request deadline `http::serve` shares with the handlers, if one is configured.
+pub fn deadline(req: &mut Request) -> Option<Duration> {
+    req.get::<::persistent::Read<Timeout>>().ok().map(|d| *d)
+}
+
+/// Returned by [`run`](fn.run.html) when a computation outlives its deadline.
+pub struct TimedOut;
+
+/// Run `work` on a dedicated worker thread, returning `Err(TimedOut)` if it does not complete
+/// within `deadline`. The worker thread is detached, not killed, on timeout (see the module note).
+pub fn run<F, T>(deadline: Duration, work: F) -> Result<T, TimedOut>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let (tx, rx) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        // If the receiver has already hung up the request timed out; drop the result on the floor.
+        let _ = tx.send(work());
+    });
+
+    match rx.recv_timeout(deadline) {
+        Ok(value) => Ok(value),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(TimedOut),
+        // The sender was dropped without a value, so `work` panicked: re-raise the panic on this
+        // thread rather than reporting it as a timeout.
+        Err(mpsc::RecvTimeoutError::Disconnected) => match worker.join() {
+            Err(payload) => ::std::panic::resume_unwind(payload),
+            Ok(()) => unreachable!("worker dropped its sender without panicking"),
+        },
+    }
+}
+
+/// Run a handler's engine call, bounded by the request's deadline when one is configured. The
+/// engine is locked on the worker thread so an over-running query releases the Iron worker; on
+/// expiry the returned `Err` is a ready-to-propagate `408` (see [`timed_out`](fn.timed_out.html)).
+pub fn dispatch<T, F>(req: &mut Request, work: F) -> IronResult<T>
+    where F: FnOnce(&SemanticEngine) -> T + Send + 'static,
+          T: Send + 'static
+{
+    let deadline = deadline(req);
+    let engine = req.extensions.get::<EngineProvider>().unwrap().clone();
+
+    let call = move || {
+        let engine = engine.lock().unwrap();
+        work(&**engine)
+    };
+
+    match deadline {
+        Some(deadline) => run(deadline, call).map_err(|_| timed_out()),
+        None => Ok(call()),
+    }
+}
+
+/// `408 Request Timeout` for a computation that blew past its deadline.
+pub fn timed_out() -> IronError {
+    use std::io::{Error, ErrorKind};
+    let err = Error::new(ErrorKind::TimedOut, "request processing exceeded the configured timeout");
+    IronError::new(err, status::RequestTimeout)
+}